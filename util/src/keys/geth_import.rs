@@ -16,39 +16,107 @@
 
 //! Geth keys import/export tool
 
+use std::path::PathBuf;
+use std::io::Write;
+use time;
+use crypto::scrypt::{scrypt, ScryptParams as ScryptKdfParams};
+use crypto::pbkdf2::pbkdf2;
+use crypto::hmac::Hmac;
+use crypto::sha2::Sha256;
+use crypto::aes::{self, KeySize};
+use crypto::symmetriccipher::SynchronousStreamCipher;
 use common::*;
-use keys::store::SecretStore;
-use keys::directory::KeyFileContent;
+use keys::store::{SecretStore, SecretVaultRef};
+use keys::directory::{KeyFileContent, KeyFileCrypto, KeyFileKdf};
 
-/// Enumerates all geth keys in the directory and returns collection of tuples `(accountId, filename)`
+/// Enumerates all geth keys under the directory, recursing into subdirectories, and returns a
+/// collection of tuples `(accountId, filename)` where `filename` is the path of the key file
+/// relative to `path` (using `/` as a separator for nested files), keeping the `(Address,
+/// String)` shape the rest of the import pipeline already expects.
+///
+/// Files named with geth's `name--name--address` convention are recognised from their filename;
+/// anything else is peeked at for a JSON `"address"` field instead, so nested or oddly-named
+/// keystore layouts are still picked up. Entries that match neither are skipped rather than
+/// aborting the whole enumeration.
 pub fn enumerate_geth_keys(path: &Path) -> Result<Vec<(Address, String)>, io::Error> {
 	let mut entries = Vec::new();
-	for entry in try!(fs::read_dir(path)) {
+	try!(enumerate_geth_keys_into(path, path, &mut entries));
+	Ok(entries)
+}
+
+fn enumerate_geth_keys_into(root: &Path, dir: &Path, entries: &mut Vec<(Address, String)>) -> Result<(), io::Error> {
+	for entry in try!(fs::read_dir(dir)) {
 		let entry = try!(entry);
-		if !try!(fs::metadata(entry.path())).is_dir() {
-			match entry.file_name().to_str() {
-				Some(name) => {
-					let parts: Vec<&str> = name.split("--").collect();
-					if parts.len() != 3 { continue; }
-					match Address::from_str(parts[2]) {
-						Ok(account_id) => { entries.push((account_id, name.to_owned())); }
-						Err(e) => { panic!("error: {:?}", e); }
-					}
-				},
-				None => { continue; }
-			};
+		let entry_path = entry.path();
+		if try!(fs::metadata(&entry_path)).is_dir() {
+			try!(enumerate_geth_keys_into(root, &entry_path, entries));
+			continue;
+		}
+
+		let relative = match entry_path.strip_prefix(root).ok().and_then(|relative| relative.to_str()) {
+			Some(relative) => relative.to_owned(),
+			None => continue,
+		};
+
+		if let Some(address) = address_from_geth_filename(&entry_path) {
+			entries.push((address, relative));
+			continue;
+		}
+
+		if let Some(address) = address_from_keystore_contents(&entry_path) {
+			entries.push((address, relative));
 		}
 	}
-	Ok(entries)
+	Ok(())
+}
+
+/// Parses an address out of geth's `name--name--address` filename convention.
+/// Returns `None` (instead of panicking) when the name doesn't follow the convention.
+fn address_from_geth_filename(path: &Path) -> Option<Address> {
+	let name = match path.file_name().and_then(|name| name.to_str()) {
+		Some(name) => name,
+		None => return None,
+	};
+	let parts: Vec<&str> = name.split("--").collect();
+	if parts.len() != 3 { return None; }
+	Address::from_str(parts[2]).ok()
+}
+
+/// Falls back to reading the keystore JSON's own `"address"` field, for files that don't
+/// follow geth's filename convention.
+fn address_from_keystore_contents(path: &Path) -> Option<Address> {
+	let mut file = match fs::File::open(path) {
+		Ok(file) => file,
+		Err(_) => return None,
+	};
+	let mut buf = String::new();
+	if file.read_to_string(&mut buf).is_err() { return None; }
+	let json = match Json::from_str(&buf) {
+		Ok(json) => json,
+		Err(_) => return None,
+	};
+	json.as_object()
+		.and_then(|object| object.get("address"))
+		.and_then(|address| address.as_string())
+		.and_then(|address| Address::from_str(address).ok())
 }
 
 /// Geth import error
+///
+/// Note: the old undifferentiated `FormatError` variant has been replaced by `MalformedJson`
+/// and `UnknownKdf` below so callers can tell a bad file apart from an unsupported kdf. This
+/// is an intentional, breaking rename of this enum's variants — callers matching on the old
+/// `FormatError` name need to switch to one (or both) of the new variants.
 #[derive(Debug)]
 pub enum ImportError {
 	/// Io error reading geth file
 	IoError(io::Error),
-	/// format error
-	FormatError,
+	/// the geth keystore JSON could not be parsed, or was missing required fields
+	MalformedJson,
+	/// the keystore specifies a kdf other than `scrypt` or `pbkdf2`
+	UnknownKdf,
+	/// the keystore's MAC did not match the ciphertext (wrong password, or a corrupted/tampered file)
+	MacMismatch,
 }
 
 impl From<io::Error> for ImportError {
@@ -57,40 +125,233 @@ impl From<io::Error> for ImportError {
 	}
 }
 
-/// Imports one geth key to the store
-pub fn import_geth_key(secret_store: &mut SecretStore, geth_keyfile_path: &Path) -> Result<(), ImportError> {
+/// Reads a geth keystore file off disk and parses it into a `KeyFileContent`,
+/// translating geth's capitalized `"Crypto"` object into parity's own `"crypto"`.
+fn load_geth_key_file(geth_keyfile_path: &Path) -> Result<KeyFileContent, ImportError> {
 	let mut file = try!(fs::File::open(geth_keyfile_path));
 	let mut buf = String::new();
 	try!(file.read_to_string(&mut buf));
 
 	let mut json_result = Json::from_str(&buf);
 	let mut json = match json_result {
-		Ok(ref mut parsed_json) => try!(parsed_json.as_object_mut().ok_or(ImportError::FormatError)),
-		Err(_) => { return Err(ImportError::FormatError); }
+		Ok(ref mut parsed_json) => try!(parsed_json.as_object_mut().ok_or(ImportError::MalformedJson)),
+		Err(_) => { return Err(ImportError::MalformedJson); }
 	};
-	let crypto_object = try!(json.get("Crypto").and_then(|crypto| crypto.as_object()).ok_or(ImportError::FormatError)).clone();
+	let crypto_object = try!(json.get("Crypto").and_then(|crypto| crypto.as_object()).ok_or(ImportError::MalformedJson)).clone();
+
+	match crypto_object.get("kdf").and_then(|kdf| kdf.as_string()) {
+		Some("scrypt") | Some("pbkdf2") => (),
+		Some(_) => { return Err(ImportError::UnknownKdf); },
+		None => { return Err(ImportError::MalformedJson); }
+	}
+
 	json.insert("crypto".to_owned(), Json::Object(crypto_object));
 	json.remove("Crypto");
-	match KeyFileContent::load(&Json::Object(json.clone())) {
-		Ok(key_file) => try!(secret_store.import_key(key_file)),
-		Err(_) => { return Err(ImportError::FormatError); }
-	};
+	KeyFileContent::load(&Json::Object(json.clone())).map_err(|_| ImportError::MalformedJson)
+}
+
+/// Derives the AES key material for a keystore's crypto section, running whichever
+/// KDF (scrypt or pbkdf2-hmac-sha256) the file specifies.
+fn derive_geth_key(crypto: &KeyFileCrypto, password: &str) -> Vec<u8> {
+	match crypto.kdf {
+		KeyFileKdf::Scrypt(ref params) => {
+			let mut derived = vec![0u8; params.dklen as usize];
+			let log2_n = (params.n as u64).trailing_zeros() as u8;
+			let scrypt_params = ScryptKdfParams::new(log2_n, params.r, params.p);
+			scrypt(password.as_bytes(), &params.salt, &scrypt_params, &mut derived);
+			derived
+		},
+		KeyFileKdf::Pbkdf2(ref params) => {
+			let mut derived = vec![0u8; params.dklen as usize];
+			let mut hmac = Hmac::new(Sha256::new(), password.as_bytes());
+			pbkdf2(&mut hmac, &params.salt, params.c, &mut derived);
+			derived
+		},
+	}
+}
+
+/// Verifies a keystore's MAC as `Keccak256(derived_key[16..32] ++ ciphertext)`.
+///
+/// `derived`'s length tracks the keystore's own `dklen`, which is taken verbatim from
+/// untrusted JSON, so a corrupted or tampered file can specify a `dklen` too short to slice
+/// into. That must come back as a clean `ImportError` rather than panicking and taking down
+/// a whole batch import.
+fn verify_geth_mac(crypto: &KeyFileCrypto, password: &str) -> Result<bool, ImportError> {
+	let derived = derive_geth_key(crypto, password);
+	if derived.len() < 32 {
+		return Err(ImportError::MalformedJson);
+	}
+	let mut mac_input = derived[16..32].to_vec();
+	mac_input.extend_from_slice(&crypto.ciphertext);
+	Ok(mac_input.keccak256() == crypto.mac)
+}
+
+/// Decrypts a keystore's ciphertext (aes-128-ctr, keyed off the first 16 bytes of the
+/// derived key) back into the raw secret.
+///
+/// See `verify_geth_mac` for why the derived key's length needs checking before it's sliced.
+fn decrypt_geth_secret(crypto: &KeyFileCrypto, password: &str) -> Result<Bytes, ImportError> {
+	let derived = derive_geth_key(crypto, password);
+	if derived.len() < 16 {
+		return Err(ImportError::MalformedJson);
+	}
+	let mut decryptor = aes::ctr(KeySize::KeySize128, &derived[0..16], &crypto.cipherparams.iv);
+	let mut secret = vec![0u8; crypto.ciphertext.len()];
+	decryptor.process(&crypto.ciphertext, &mut secret);
+	Ok(secret)
+}
+
+/// Imports one geth key to the store
+pub fn import_geth_key(secret_store: &mut SecretStore, geth_keyfile_path: &Path) -> Result<(), ImportError> {
+	let key_file = try!(load_geth_key_file(geth_keyfile_path));
+	try!(secret_store.import_key(key_file));
+	Ok(())
+}
+
+/// Imports one geth key to the store, first verifying the keystore's MAC against `password`
+/// so that a corrupted or tampered ciphertext is rejected up front instead of failing later
+/// at decrypt time.
+pub fn import_geth_key_checked(secret_store: &mut SecretStore, geth_keyfile_path: &Path, password: &str) -> Result<(), ImportError> {
+	let key_file = try!(load_geth_key_file(geth_keyfile_path));
+	if !try!(verify_geth_mac(&key_file.crypto, password)) {
+		return Err(ImportError::MacMismatch);
+	}
+	try!(secret_store.import_key(key_file));
+	Ok(())
+}
+
+/// Imports one geth key into `vault`, decrypting it with `password` and re-encrypting the
+/// resulting secret under `target_kdf` rather than keeping geth's original KDF cost. This lets
+/// a low-cost geth file be upgraded to a stronger scrypt cost (or downgraded, for constrained
+/// devices) as it's brought into the store, instead of always landing in the default top-level
+/// keystore.
+pub fn import_geth_key_into(secret_store: &mut SecretStore, geth_keyfile_path: &Path, password: &str, vault: &SecretVaultRef, target_kdf: KeyFileKdf) -> Result<(), ImportError> {
+	let key_file = try!(load_geth_key_file(geth_keyfile_path));
+	if !try!(verify_geth_mac(&key_file.crypto, password)) {
+		return Err(ImportError::MacMismatch);
+	}
+	let secret = try!(decrypt_geth_secret(&key_file.crypto, password));
+	try!(secret_store.import_secret_into(vault, &secret, password, target_kdf));
 	Ok(())
 }
 
+/// Imports every geth key in a directory into `vault`, re-encrypting each to `target_kdf` on
+/// the way in. See `import_geth_key_into`.
+pub fn import_geth_keys_into(secret_store: &mut SecretStore, geth_keyfiles_directory: &Path, password: &str, vault: &SecretVaultRef, target_kdf: KeyFileKdf) -> Result<ImportReport, ImportError> {
+	let geth_files = try!(enumerate_geth_keys(geth_keyfiles_directory));
+	let mut report = ImportReport { imported: Vec::new(), skipped: Vec::new() };
+	for (address, file_path) in geth_files {
+		let mut path = PathBuf::new();
+		path.push(geth_keyfiles_directory);
+		path.push(file_path);
+		match import_geth_key_into(secret_store, Path::new(&path), password, vault, target_kdf.clone()) {
+			Ok(()) => report.imported.push(address),
+			Err(e) => report.skipped.push((address, e)),
+		}
+	}
+	Ok(report)
+}
+
 /// Imports all geth keys in the directory
 pub fn import_geth_keys(secret_store: &mut SecretStore, geth_keyfiles_directory: &Path) -> Result<(), ImportError> {
-	use std::path::PathBuf;
+	let report = try!(import_geth_keys_report(secret_store, geth_keyfiles_directory));
+	for &(ref address, ref error) in report.skipped.iter() {
+		warn!("Skipped geth address {}, error importing: {:?}", address, error)
+	}
+	Ok(())
+}
+
+/// The outcome of importing a directory of geth keys: which addresses were imported,
+/// and which were skipped, along with the reason each was skipped.
+#[derive(Debug)]
+pub struct ImportReport {
+	/// addresses that were successfully imported
+	pub imported: Vec<Address>,
+	/// addresses that failed to import, with the reason
+	pub skipped: Vec<(Address, ImportError)>,
+}
+
+/// Imports all geth keys in the directory, returning a structured report of what was
+/// imported and what was skipped (and why), instead of only logging the failures.
+pub fn import_geth_keys_report(secret_store: &mut SecretStore, geth_keyfiles_directory: &Path) -> Result<ImportReport, ImportError> {
 	let geth_files = try!(enumerate_geth_keys(geth_keyfiles_directory));
-	for &(ref address, ref file_path) in geth_files.iter() {
+	let mut report = ImportReport { imported: Vec::new(), skipped: Vec::new() };
+	for (address, file_path) in geth_files {
 		let mut path = PathBuf::new();
 		path.push(geth_keyfiles_directory);
 		path.push(file_path);
-		if let Err(e) = import_geth_key(secret_store, Path::new(&path)) {
-			warn!("Skipped geth address {}, error importing: {:?}", address, e)
+		match import_geth_key(secret_store, Path::new(&path)) {
+			Ok(()) => report.imported.push(address),
+			Err(e) => report.skipped.push((address, e)),
 		}
 	}
-	Ok(())
+	Ok(report)
+}
+
+/// Geth export error
+#[derive(Debug)]
+pub enum ExportError {
+	/// Io error writing geth file
+	IoError(io::Error),
+	/// requested address is not present in the store
+	AccountNotFound,
+	/// format error preparing keystore JSON
+	FormatError,
+}
+
+impl From<io::Error> for ExportError {
+	fn from (err: io::Error) -> ExportError {
+		ExportError::IoError(err)
+	}
+}
+
+/// Formats a timestamp the way geth names its keystore files, e.g.
+/// `2016-02-17T09-20-45.721400158Z`.
+fn geth_timestamp() -> String {
+	let now = time::now_utc();
+	let formatted = time::strftime("%Y-%m-%dT%H-%M-%S", &now).expect("valid time format string");
+	format!("{}.{:09}Z", formatted, now.tm_nsec)
+}
+
+/// Exports one account from the store as a geth v3 keystore file, written into `out_dir`.
+/// Returns the path of the file written.
+pub fn export_geth_key(secret_store: &SecretStore, address: &Address, out_dir: &Path) -> Result<PathBuf, ExportError> {
+	let uuid = try!(secret_store.account(address).ok_or(ExportError::AccountNotFound));
+	let key_file = try!(secret_store.directory().get(&uuid).ok_or(ExportError::AccountNotFound));
+
+	let mut json = match key_file.to_json() {
+		Json::Object(object) => object,
+		_ => { return Err(ExportError::FormatError); }
+	};
+
+	let crypto_object = try!(json.get("crypto").and_then(|crypto| crypto.as_object()).ok_or(ExportError::FormatError)).clone();
+	json.insert("Crypto".to_owned(), Json::Object(crypto_object));
+	json.remove("crypto");
+	json.insert("address".to_owned(), Json::String(format!("{}", address)));
+	json.insert("version".to_owned(), Json::U64(3));
+
+	let file_name = format!("UTC--{}--{}", geth_timestamp(), address);
+	let mut path = PathBuf::new();
+	path.push(out_dir);
+	path.push(&file_name);
+
+	let mut file = try!(fs::File::create(&path));
+	try!(file.write_all(Json::Object(json).to_string().as_bytes()));
+	Ok(path)
+}
+
+/// Exports several accounts from the store as geth v3 keystore files, written into `out_dir`.
+/// Addresses that are not present in the store, or that fail to serialize, are skipped with
+/// a warning rather than aborting the whole batch.
+pub fn export_geth_keys(secret_store: &SecretStore, addresses: &[Address], out_dir: &Path) -> Result<Vec<PathBuf>, ExportError> {
+	let mut exported = Vec::new();
+	for address in addresses {
+		match export_geth_key(secret_store, address, out_dir) {
+			Ok(path) => exported.push(path),
+			Err(e) => warn!("Skipped exporting address {}, error: {:?}", address, e),
+		}
+	}
+	Ok(exported)
 }
 
 #[cfg(test)]
@@ -103,7 +364,35 @@ mod tests {
 	#[test]
 	fn can_enumerate() {
 		let keys = enumerate_geth_keys(Path::new("res/geth_keystore")).unwrap();
+		assert_eq!(3, keys.len());
+	}
+
+	#[test]
+	fn enumerates_nested_directories_and_skips_junk() {
+		use std::fs;
+
+		let temp = ::devtools::RandomTempPath::create_dir();
+		let nested = temp.as_path().join("nested");
+		fs::create_dir_all(&nested).unwrap();
+
+		// standard geth naming, one level down
+		fs::copy(
+			"res/geth_keystore/UTC--2016-02-17T09-20-45.721400158Z--3f49624084b67849c7b4e805c5988c21a430f9d9",
+			nested.join("UTC--2016-02-17T09-20-45.721400158Z--3f49624084b67849c7b4e805c5988c21a430f9d9")).unwrap();
+
+		// an oddly-named file that still has a valid `"address"` field
+		fs::copy(
+			"res/geth_keystore/UTC--2017-03-20T17-03-23.000000000Z--008aeeda4d805471df9b2a5b0f38a0c3bcba786b",
+			temp.as_path().join("not-geth-named.json")).unwrap();
+
+		// junk that should be skipped rather than panic the whole enumeration
+		fs::File::create(temp.as_path().join("not--a--keystore")).unwrap();
+		fs::File::create(temp.as_path().join("definitely-not-json")).unwrap();
+
+		let keys = enumerate_geth_keys(temp.as_path()).unwrap();
 		assert_eq!(2, keys.len());
+		assert!(keys.iter().any(|&(address, _)| address == Address::from_str("3f49624084b67849c7b4e805c5988c21a430f9d9").unwrap()));
+		assert!(keys.iter().any(|&(address, _)| address == Address::from_str("008aeeda4d805471df9b2a5b0f38a0c3bcba786b").unwrap()));
 	}
 
 	#[test]
@@ -128,6 +417,17 @@ mod tests {
 		assert!(key.is_some());
 	}
 
+	#[test]
+	fn reports_imported_and_skipped_addresses() {
+		let temp = ::devtools::RandomTempPath::create_dir();
+		let mut secret_store = SecretStore::new_in(temp.as_path());
+		let report = import_geth_keys_report(&mut secret_store, Path::new("res/geth_keystore")).unwrap();
+
+		assert_eq!(3, report.imported.len());
+		assert!(report.skipped.is_empty());
+		assert!(report.imported.contains(&Address::from_str("3f49624084b67849c7b4e805c5988c21a430f9d9").unwrap()));
+	}
+
 	#[test]
 	fn imports_as_scrypt_keys() {
 		use keys::directory::{KeyDirectory, KeyFileKdf};
@@ -150,6 +450,64 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn can_import_pbkdf2_key() {
+		let temp = ::devtools::RandomTempPath::create_dir();
+		let mut secret_store = SecretStore::new_in(temp.as_path());
+		import_geth_key(&mut secret_store, Path::new("res/geth_keystore/UTC--2017-03-20T17-03-23.000000000Z--008aeeda4d805471df9b2a5b0f38a0c3bcba786b")).unwrap();
+
+		let key = secret_store.account(&Address::from_str("008aeeda4d805471df9b2a5b0f38a0c3bcba786b").unwrap());
+		assert!(key.is_some());
+	}
+
+	#[test]
+	fn imports_as_pbkdf2_keys() {
+		use keys::directory::{KeyDirectory, KeyFileKdf};
+
+		let temp = ::devtools::RandomTempPath::create_dir();
+		{
+			let mut secret_store = SecretStore::new_in(temp.as_path());
+			import_geth_key(&mut secret_store, Path::new("res/geth_keystore/UTC--2017-03-20T17-03-23.000000000Z--008aeeda4d805471df9b2a5b0f38a0c3bcba786b")).unwrap();
+		}
+
+		let key_directory = KeyDirectory::new(&temp.as_path());
+		let key_file = key_directory.get(&H128::from_str("3198bc9c66725ab3d9954942343ae5b6").unwrap()).unwrap();
+
+		match key_file.crypto.kdf {
+			KeyFileKdf::Pbkdf2(pbkdf2_params) => {
+				assert_eq!(262144, pbkdf2_params.c);
+				assert_eq!(32, pbkdf2_params.dklen);
+			},
+			_ => { panic!("expected kdf params of crypto to be of pbkdf2 type"); }
+		}
+	}
+
+	#[test]
+	fn rejects_unknown_kdf() {
+		let temp_dir = ::devtools::RandomTempPath::create_dir();
+		let bad_file_path = temp_dir.as_path().join("UTC--2017-01-01T00-00-00.000000000Z--0000000000000000000000000000000000000000");
+		let mut file = fs::File::create(&bad_file_path).unwrap();
+		file.write_all(br#"{
+			"address": "0000000000000000000000000000000000000000",
+			"Crypto": {
+				"cipher": "aes-128-ctr",
+				"ciphertext": "00",
+				"cipherparams": { "iv": "00" },
+				"kdf": "bcrypt",
+				"kdfparams": {},
+				"mac": "00"
+			},
+			"id": "00000000-0000-0000-0000-000000000000",
+			"version": 3
+		}"#).unwrap();
+
+		let mut secret_store = SecretStore::new_in(temp_dir.as_path());
+		match import_geth_key(&mut secret_store, &bad_file_path) {
+			Err(ImportError::UnknownKdf) => (),
+			other => panic!("expected UnknownKdf, got {:?}", other),
+		}
+	}
+
 	#[test]
 	fn can_decrypt_with_imported() {
 		use keys::store::EncryptedHashMap;
@@ -162,4 +520,127 @@ mod tests {
 		assert!(val.is_ok());
 		assert_eq!(32, val.unwrap().len());
 	}
+
+	#[test]
+	fn can_export_and_reimport() {
+		let import_temp = ::devtools::RandomTempPath::create_dir();
+		let export_temp = ::devtools::RandomTempPath::create_dir();
+		let address = Address::from_str("3f49624084b67849c7b4e805c5988c21a430f9d9").unwrap();
+
+		let mut secret_store = SecretStore::new_in(import_temp.as_path());
+		import_geth_key(&mut secret_store, Path::new("res/geth_keystore/UTC--2016-02-17T09-20-45.721400158Z--3f49624084b67849c7b4e805c5988c21a430f9d9")).unwrap();
+
+		let exported_path = export_geth_key(&secret_store, &address, export_temp.as_path()).unwrap();
+
+		let file_name = exported_path.file_name().and_then(|name| name.to_str()).unwrap().to_owned();
+		assert!(file_name.ends_with("--3f49624084b67849c7b4e805c5988c21a430f9d9"));
+		assert!(!file_name.contains("0x"));
+
+		let mut exported_file = fs::File::open(&exported_path).unwrap();
+		let mut exported_json = String::new();
+		exported_file.read_to_string(&mut exported_json).unwrap();
+		let parsed = Json::from_str(&exported_json).unwrap();
+		let exported_address = parsed.as_object().unwrap().get("address").and_then(|a| a.as_string()).unwrap();
+		assert_eq!("3f49624084b67849c7b4e805c5988c21a430f9d9", exported_address);
+
+		let mut reimport_store = SecretStore::new_in(::devtools::RandomTempPath::create_dir().as_path());
+		import_geth_key(&mut reimport_store, &exported_path).unwrap();
+
+		let key = reimport_store.account(&address);
+		assert!(key.is_some());
+	}
+
+	#[test]
+	fn checked_import_rejects_wrong_password() {
+		let temp = ::devtools::RandomTempPath::create_dir();
+		let mut secret_store = SecretStore::new_in(temp.as_path());
+		let result = import_geth_key_checked(
+			&mut secret_store,
+			Path::new("res/geth_keystore/UTC--2016-02-17T09-20-45.721400158Z--3f49624084b67849c7b4e805c5988c21a430f9d9"),
+			"not-the-password");
+		match result {
+			Err(ImportError::MacMismatch) => (),
+			other => panic!("expected MacMismatch, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn checked_import_accepts_correct_password() {
+		let temp = ::devtools::RandomTempPath::create_dir();
+		let mut secret_store = SecretStore::new_in(temp.as_path());
+		import_geth_key_checked(
+			&mut secret_store,
+			Path::new("res/geth_keystore/UTC--2016-02-17T09-20-45.721400158Z--3f49624084b67849c7b4e805c5988c21a430f9d9"),
+			"123").unwrap();
+
+		let key = secret_store.account(&Address::from_str("3f49624084b67849c7b4e805c5988c21a430f9d9").unwrap());
+		assert!(key.is_some());
+	}
+
+	#[test]
+	fn rejects_short_dklen_without_panicking() {
+		let temp = ::devtools::RandomTempPath::create_dir();
+		let mut keyfile_path = temp.as_path().to_owned();
+		keyfile_path.push("short-dklen.json");
+		{
+			let mut file = fs::File::create(&keyfile_path).unwrap();
+			file.write_all(br#"{
+				"address": "3f49624084b67849c7b4e805c5988c21a430f9d9",
+				"Crypto": {
+					"cipher": "aes-128-ctr",
+					"ciphertext": "aabbccdd",
+					"cipherparams": {
+						"iv": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+					},
+					"kdf": "scrypt",
+					"kdfparams": {
+						"dklen": 8,
+						"n": 1024,
+						"r": 8,
+						"p": 1,
+						"salt": "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+					},
+					"mac": "0382647605269ff68a320ba4aa619a479fb7a1b9f874feb9197a28fb4c71fa78"
+				},
+				"id": "62a0ad73-556d-496a-8e1c-0783d30d3ace",
+				"version": 3
+			}"#).unwrap();
+		}
+
+		let mut secret_store = SecretStore::new_in(temp.as_path());
+		let result = import_geth_key_checked(&mut secret_store, &keyfile_path, "123");
+		match result {
+			Err(ImportError::MalformedJson) => (),
+			other => panic!("expected MalformedJson, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn can_import_into_vault_with_target_kdf() {
+		use keys::directory::{KeyDirectory, ScryptParams};
+		use keys::store::SecretVaultRef;
+
+		let temp = ::devtools::RandomTempPath::create_dir();
+		let geth_keyfile_path = Path::new("res/geth_keystore/UTC--2016-02-17T09-20-45.721400158Z--3f49624084b67849c7b4e805c5988c21a430f9d9");
+
+		let source = load_geth_key_file(geth_keyfile_path).unwrap();
+		let target_kdf = match source.crypto.kdf {
+			KeyFileKdf::Scrypt(params) => KeyFileKdf::Scrypt(ScryptParams { n: 1024, ..params }),
+			other => other,
+		};
+
+		let uuid = {
+			let mut secret_store = SecretStore::new_in(temp.as_path());
+			import_geth_key_into(&mut secret_store, geth_keyfile_path, "123", &SecretVaultRef::Root, target_kdf).unwrap();
+			secret_store.account(&Address::from_str("3f49624084b67849c7b4e805c5988c21a430f9d9").unwrap()).unwrap()
+		};
+
+		let key_directory = KeyDirectory::new(&temp.as_path());
+		let key_file = key_directory.get(&uuid).unwrap();
+
+		match key_file.crypto.kdf {
+			KeyFileKdf::Scrypt(scrypt_params) => assert_eq!(1024, scrypt_params.n),
+			_ => panic!("expected target_kdf (scrypt) to have been applied to the persisted key"),
+		}
+	}
 }
\ No newline at end of file